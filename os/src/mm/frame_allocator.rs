@@ -0,0 +1,96 @@
+//! Physical frame allocation: a stack allocator over the free physical
+//! memory range, handing out frames wrapped in an RAII [`FrameTracker`].
+
+use super::address::PhysPageNum;
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+trait FrameAllocatorImpl {
+    fn new() -> Self;
+    fn alloc(&mut self) -> Option<PhysPageNum>;
+    fn dealloc(&mut self, ppn: PhysPageNum);
+}
+
+struct StackFrameAllocator {
+    current: usize,
+    end: usize,
+    recycled: Vec<usize>,
+}
+
+impl StackFrameAllocator {
+    pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
+        self.current = l.0;
+        self.end = r.0;
+    }
+}
+
+impl FrameAllocatorImpl for StackFrameAllocator {
+    fn new() -> Self {
+        Self {
+            current: 0,
+            end: 0,
+            recycled: Vec::new(),
+        }
+    }
+    fn alloc(&mut self) -> Option<PhysPageNum> {
+        if let Some(ppn) = self.recycled.pop() {
+            Some(ppn.into())
+        } else if self.current == self.end {
+            None
+        } else {
+            self.current += 1;
+            Some((self.current - 1).into())
+        }
+    }
+    fn dealloc(&mut self, ppn: PhysPageNum) {
+        let ppn = ppn.0;
+        assert!(
+            ppn < self.current && !self.recycled.iter().any(|&v| v == ppn),
+            "frame ppn={:#x} has not been allocated!",
+            ppn
+        );
+        self.recycled.push(ppn);
+    }
+}
+
+lazy_static! {
+    static ref FRAME_ALLOCATOR: UPSafeCell<StackFrameAllocator> =
+        unsafe { UPSafeCell::new(StackFrameAllocator::new()) };
+}
+
+/// Initialize the frame allocator over the free physical memory range.
+/// Called once during boot, before the first `frame_alloc`.
+pub fn init_frame_allocator(l: PhysPageNum, r: PhysPageNum) {
+    FRAME_ALLOCATOR.exclusive_access().init(l, r);
+}
+
+/// An RAII handle for an allocated physical frame: zeroed on allocation,
+/// returned to the allocator automatically when dropped.
+pub struct FrameTracker {
+    pub ppn: PhysPageNum,
+}
+
+impl FrameTracker {
+    pub fn new(ppn: PhysPageNum) -> Self {
+        for byte in ppn.get_bytes_array().iter_mut() {
+            *byte = 0;
+        }
+        Self { ppn }
+    }
+}
+
+impl Drop for FrameTracker {
+    fn drop(&mut self) {
+        frame_dealloc(self.ppn);
+    }
+}
+
+/// Allocate a zeroed physical frame, or `None` if memory is exhausted.
+pub fn frame_alloc() -> Option<FrameTracker> {
+    FRAME_ALLOCATOR.exclusive_access().alloc().map(FrameTracker::new)
+}
+
+fn frame_dealloc(ppn: PhysPageNum) {
+    FRAME_ALLOCATOR.exclusive_access().dealloc(ppn);
+}
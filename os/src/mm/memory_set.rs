@@ -0,0 +1,274 @@
+//! Address spaces: a `MemorySet` is a page table plus the list of
+//! [`MapArea`]s mapped into it, covering kernel and user address spaces
+//! alike.
+
+use super::address::{VirtAddr, VirtPageNum};
+use super::frame_allocator::{frame_alloc, FrameTracker};
+use super::page_table::{PTEFlags, PageTable, PageTableEntry};
+use crate::config::PAGE_SIZE;
+use crate::sync::UPSafeCell;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use bitflags::*;
+use lazy_static::*;
+
+bitflags! {
+    /// The subset of `PTEFlags` callers outside `mm` ever choose; the page
+    /// table itself manages the V/A/D/G bits. Bit positions are chosen to
+    /// line up directly with `PTEFlags` so converting between the two is a
+    /// plain bit-cast.
+    pub struct MapPermission: u8 {
+        const R = 1 << 1;
+        const W = 1 << 2;
+        const X = 1 << 3;
+        const U = 1 << 4;
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MapType {
+    /// Every page is backed by a `FrameTracker` mapped up front.
+    Framed,
+    /// The virtual range is reserved but left unmapped until a page fault
+    /// within it is resolved by `MemorySet::alloc_lazy_frame`.
+    Lazy,
+}
+
+/// A contiguous virtual range mapped with uniform type and permissions.
+pub struct MapArea {
+    start_vpn: VirtPageNum,
+    end_vpn: VirtPageNum,
+    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    map_type: MapType,
+    map_perm: MapPermission,
+}
+
+impl MapArea {
+    pub fn new(start_va: VirtAddr, end_va: VirtAddr, map_type: MapType, map_perm: MapPermission) -> Self {
+        Self {
+            start_vpn: start_va.floor(),
+            end_vpn: end_va.ceil(),
+            data_frames: BTreeMap::new(),
+            map_type,
+            map_perm,
+        }
+    }
+    fn contains(&self, vpn: VirtPageNum) -> bool {
+        vpn.0 >= self.start_vpn.0 && vpn.0 < self.end_vpn.0
+    }
+    fn overlaps(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        self.start_vpn.0 < end_vpn.0 && start_vpn.0 < self.end_vpn.0
+    }
+    /// Map a single page. A no-op for `Lazy` areas: their pages are only
+    /// mapped once a fault in them reaches `alloc_one_lazily`.
+    fn map_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        if self.map_type != MapType::Framed {
+            return;
+        }
+        let frame = frame_alloc().unwrap();
+        let ppn = frame.ppn;
+        self.data_frames.insert(vpn, frame);
+        page_table.map(vpn, ppn, PTEFlags::from(self.map_perm));
+    }
+    fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        if self.data_frames.remove(&vpn).is_some() {
+            page_table.unmap(vpn);
+        }
+    }
+    /// Allocate and map the page backing `vpn`. Used by `alloc_lazy_frame`
+    /// once a page fault has been matched to this (necessarily `Lazy`) area.
+    fn alloc_one_lazily(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        let frame = frame_alloc().unwrap();
+        let ppn = frame.ppn;
+        self.data_frames.insert(vpn, frame);
+        page_table.map(vpn, ppn, PTEFlags::from(self.map_perm));
+    }
+    fn map(&mut self, page_table: &mut PageTable) {
+        for vpn in self.start_vpn.0..self.end_vpn.0 {
+            self.map_one(page_table, VirtPageNum(vpn));
+        }
+    }
+    fn unmap(&mut self, page_table: &mut PageTable) {
+        for vpn in self.start_vpn.0..self.end_vpn.0 {
+            self.unmap_one(page_table, VirtPageNum(vpn));
+        }
+    }
+}
+
+/// An address space: a page table plus every area mapped into it.
+pub struct MemorySet {
+    page_table: PageTable,
+    areas: Vec<MapArea>,
+}
+
+impl MemorySet {
+    pub fn new_bare() -> Self {
+        Self {
+            page_table: PageTable::new(),
+            areas: Vec::new(),
+        }
+    }
+    pub fn token(&self) -> usize {
+        self.page_table.token()
+    }
+    pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
+        self.page_table.translate(vpn)
+    }
+
+    fn push(&mut self, mut area: MapArea, data: Option<&[u8]>) {
+        area.map(&mut self.page_table);
+        if let Some(data) = data {
+            self.copy_data(&area, data);
+        }
+        self.areas.push(area);
+    }
+    fn copy_data(&mut self, area: &MapArea, data: &[u8]) {
+        let mut start = 0;
+        let mut vpn = area.start_vpn;
+        let len = data.len();
+        loop {
+            let src = &data[start..len.min(start + PAGE_SIZE)];
+            let dst = &mut self.page_table.translate(vpn).unwrap().ppn().get_bytes_array()[..src.len()];
+            dst.copy_from_slice(src);
+            start += PAGE_SIZE;
+            if start >= len {
+                break;
+            }
+            vpn = VirtPageNum(vpn.0 + 1);
+        }
+    }
+
+    /// Map `[start_va, end_va)` with frames allocated up front.
+    pub fn insert_framed_area(&mut self, start_va: VirtAddr, end_va: VirtAddr, perm: MapPermission) {
+        self.push(MapArea::new(start_va, end_va, MapType::Framed, perm), None);
+    }
+
+    /// Reserve `[start_va, end_va)` without allocating any frame yet: the
+    /// range is recorded exactly like a framed area except its pages stay
+    /// unbacked until a fault in them reaches `alloc_lazy_frame`.
+    pub fn insert_lazy_area(&mut self, start_va: VirtAddr, end_va: VirtAddr, perm: MapPermission) {
+        self.push(MapArea::new(start_va, end_va, MapType::Lazy, perm), None);
+    }
+
+    /// Resolve a page fault at `fault_va` caused by an access needing
+    /// `needed` permission: if it falls within a `Lazy` area of this address
+    /// space *and* that area was mapped with at least `needed`, allocate and
+    /// map the single page it faulted on and return `true`. Returns `false`
+    /// if it doesn't match any lazy area, or matches one whose permissions
+    /// don't cover this access (e.g. a store into a read-only mmap), leaving
+    /// it to the caller to treat as a genuine access violation rather than
+    /// mapping a page the access was never allowed to make in the first
+    /// place (which would just fault again on retry).
+    pub fn alloc_lazy_frame(&mut self, fault_va: VirtAddr, needed: MapPermission) -> bool {
+        let vpn = fault_va.floor();
+        let page_table = &mut self.page_table;
+        let Some(area) = self
+            .areas
+            .iter_mut()
+            .find(|area| area.map_type == MapType::Lazy && area.contains(vpn))
+        else {
+            return false;
+        };
+        if !area.map_perm.contains(needed) {
+            return false;
+        }
+        if area.data_frames.contains_key(&vpn) {
+            // already resolved by an earlier fault on the same page
+            return true;
+        }
+        area.alloc_one_lazily(page_table, vpn);
+        true
+    }
+
+    pub fn check_used(&self, start_va: VirtAddr, end_va: VirtAddr) -> bool {
+        let (l, r) = (start_va.floor(), end_va.ceil());
+        self.areas.iter().any(|area| area.overlaps(l, r))
+    }
+    pub fn check_unused(&self, start_va: VirtAddr, end_va: VirtAddr) -> bool {
+        !self.check_used(start_va, end_va)
+    }
+
+    /// Drop whichever areas overlap `[start_va, end_va)`, whether or not
+    /// their pages were ever actually faulted in — a backed `Framed` area
+    /// and one still entirely unbacked by faults are released the same way.
+    pub fn delete_area(&mut self, start_va: VirtAddr, end_va: VirtAddr) {
+        let (l, r) = (start_va.floor(), end_va.ceil());
+        let page_table = &mut self.page_table;
+        self.areas.retain_mut(|area| {
+            let overlaps = area.overlaps(l, r);
+            if overlaps {
+                area.unmap(page_table);
+            }
+            !overlaps
+        });
+    }
+
+    /// Drop the single area starting at `start_vpn`, e.g. one kernel stack
+    /// slot. Unlike `delete_area`, matches by exact start rather than by
+    /// overlap, since kernel-stack slots are never partially unmapped.
+    pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
+        if let Some(idx) = self.areas.iter().position(|area| area.start_vpn == start_vpn) {
+            let mut area = self.areas.remove(idx);
+            area.unmap(&mut self.page_table);
+        }
+    }
+
+    pub fn shrink_to(&mut self, start: VirtAddr, new_end: VirtAddr) -> bool {
+        let Some(area) = self.areas.iter_mut().find(|area| area.start_vpn == start.floor()) else {
+            return false;
+        };
+        let new_end_vpn = new_end.ceil();
+        for vpn in new_end_vpn.0..area.end_vpn.0 {
+            area.unmap_one(&mut self.page_table, VirtPageNum(vpn));
+        }
+        area.end_vpn = new_end_vpn;
+        true
+    }
+    pub fn append_to(&mut self, start: VirtAddr, new_end: VirtAddr) -> bool {
+        let Some(area) = self.areas.iter_mut().find(|area| area.start_vpn == start.floor()) else {
+            return false;
+        };
+        let new_end_vpn = new_end.ceil();
+        for vpn in area.end_vpn.0..new_end_vpn.0 {
+            area.map_one(&mut self.page_table, VirtPageNum(vpn));
+        }
+        area.end_vpn = new_end_vpn;
+        true
+    }
+
+    /// Deep-copy `user_space` into a fresh address space with the same
+    /// mappings and contents, used by `fork`.
+    pub fn from_existing_user(user_space: &Self) -> Self {
+        let mut memory_set = Self::new_bare();
+        for area in user_space.areas.iter() {
+            let new_area = MapArea::new(
+                VirtAddr::from(area.start_vpn),
+                VirtAddr::from(area.end_vpn),
+                area.map_type,
+                area.map_perm,
+            );
+            memory_set.push(new_area, None);
+            for (&vpn, src_frame) in area.data_frames.iter() {
+                // `push` already mapped every page of a `Framed` area; a
+                // `Lazy` area only gets the pages the parent had actually
+                // faulted in, mapped here the same way `alloc_lazy_frame`
+                // would resolve them.
+                if area.map_type == MapType::Lazy {
+                    let page_table = &mut memory_set.page_table;
+                    let new_area = memory_set.areas.last_mut().unwrap();
+                    new_area.alloc_one_lazily(page_table, vpn);
+                }
+                let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
+                dst_ppn.get_bytes_array().copy_from_slice(src_frame.ppn.get_bytes_array());
+            }
+        }
+        memory_set
+    }
+}
+
+lazy_static! {
+    /// The kernel's own address space, shared by every process's kernel
+    /// stack and trap entry.
+    pub static ref KERNEL_SPACE: UPSafeCell<MemorySet> =
+        unsafe { UPSafeCell::new(MemorySet::new_bare()) };
+}
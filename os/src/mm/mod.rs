@@ -0,0 +1,13 @@
+//! Memory management: SV39 address types, physical frame allocation, page
+//! tables, and the address-space (`MemorySet`) abstraction built on top of
+//! them.
+
+pub mod address;
+mod frame_allocator;
+mod memory_set;
+pub mod page_table;
+
+pub use address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
+pub use frame_allocator::{frame_alloc, init_frame_allocator, FrameTracker};
+pub use memory_set::{MapPermission, MapType, MemorySet, KERNEL_SPACE};
+pub use page_table::{translated_byte_buffer, translated_refmut, translated_str, PageTable, PageTableEntry};
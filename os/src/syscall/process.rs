@@ -1,6 +1,7 @@
 //! Process management syscalls
 use crate::{
     config::MAX_SYSCALL_NUM,
+    mm::{translated_refmut, translated_str},
     task::*,
     timer::{get_time_ms, get_time_us},
 };
@@ -26,10 +27,48 @@ pub struct TaskInfo {
 /// task exits and submit an exit code
 pub fn sys_exit(exit_code: i32) -> ! {
     trace!("[kernel] Application exited with code {}", exit_code);
-    exit_current_and_run_next();
+    exit_current_and_run_next(exit_code);
     panic!("Unreachable in sys_exit!");
 }
 
+/// get the pid of the current process
+pub fn sys_getpid() -> isize {
+    trace!("kernel: sys_getpid");
+    current_pid() as isize
+}
+
+/// fork the current process into a child, which inherits a copy of its
+/// address space; returns the child's pid to the parent and 0 to the child,
+/// or -1 if the current process is multithreaded (unsupported)
+pub fn sys_fork() -> isize {
+    trace!("kernel: sys_fork");
+    fork().map_or(-1, |pid| pid as isize)
+}
+
+/// replace the current process's address space with the named app's elf
+/// image; returns -1 if no app with that name exists
+pub fn sys_exec(path: *const u8) -> isize {
+    trace!("kernel: sys_exec");
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    exec(&path)
+}
+
+/// non-blocking: look for a child process (`pid == -1` for any child) that
+/// has become a zombie, write its exit code to `exit_code_ptr`, and reap
+/// it, returning its pid. Returns -1 if no such child exists at all, or -2
+/// if matching children exist but none have exited yet; callers loop
+/// (typically via `sys_yield`) until the result is no longer -2.
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+    trace!("kernel: sys_waitpid");
+    if exit_code_ptr.is_null() {
+        waitpid(pid, core::ptr::null_mut())
+    } else {
+        let token = current_user_token();
+        waitpid(pid, translated_refmut(token, exit_code_ptr) as *mut i32)
+    }
+}
+
 /// current task gives up resources for other tasks
 pub fn sys_yield() -> isize {
     trace!("kernel: sys_yield");
@@ -54,18 +93,42 @@ pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> isize {
 pub fn sys_task_info(_ti: *mut TaskInfo) -> isize {
     trace!("kernel: sys_task_info");
     unsafe {
-        let task_info = &mut *_ti;
-
-        task_info.status = get_current_status();
-        task_info.syscall_times = get_currtask_syscall_time();
-        task_info.time = get_time_ms() - get_currtask_first_scheduled_time();
+        *_ti = get_task_info();
     }
     0
 }
 
+/// set the priority of the current task for stride scheduling, rejecting
+/// values below 2; returns the new priority, or -1 on an invalid argument
+pub fn sys_set_priority(prio: isize) -> isize {
+    trace!("kernel: sys_set_priority");
+    set_priority(prio)
+}
+
+/// spawn a new thread of the current process starting at `entry` with `arg`
+/// in `a0`; returns the new thread's tid
+pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
+    trace!("kernel: sys_thread_create");
+    thread_create(entry, arg)
+}
+
+/// get the tid of the current thread
+pub fn sys_gettid() -> isize {
+    trace!("kernel: sys_gettid");
+    gettid() as isize
+}
+
+/// wait for thread `tid` of the current process to exit, returning its exit
+/// code; returns -1 if `tid` is the caller's own tid or does not name a
+/// thread of this process, or -2 if it exists but hasn't exited yet
+pub fn sys_waittid(tid: usize) -> isize {
+    trace!("kernel: sys_waittid");
+    waittid(tid)
+}
+
 pub fn load_initial_info(syscall_id: usize) {
     //如果没被调用过：is_scheduled == false,改成true，并记录第一次被调度
-    schedule_marking();
+    schedule_mark();
     //记录调用种类和次数
-    record_this_call(syscall_id);
+    record_syscall_time(syscall_id);
 }
\ No newline at end of file
@@ -0,0 +1,160 @@
+//! Thread (tid) and per-thread user-resource allocation
+//!
+//! Each process owns one [`RecycleAllocator`] handing out `tid`s to its own
+//! threads, independently of every other process's. A thread's user stack
+//! and trap-context page are not separate mappings chosen ad hoc: both are
+//! derived from `tid` via a fixed stride within the process's shared
+//! `MemorySet`, so a newly created thread can be placed without consulting
+//! any other thread.
+
+use super::process::ProcessControlBlock;
+use crate::config::{PAGE_SIZE, TRAP_CONTEXT_BASE, USER_STACK_SIZE};
+use crate::mm::{PhysPageNum, VirtAddr};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+/// A simple id allocator: hand out the next unused integer, reusing
+/// recycled ids before growing further. Shared shape with the pid and
+/// kernel-stack allocators, but each process owns its own instance here so
+/// `tid`s only need to be unique within a process.
+pub struct RecycleAllocator {
+    current: usize,
+    recycled: Vec<usize>,
+}
+
+impl RecycleAllocator {
+    /// Create an allocator that starts handing out ids from 0.
+    pub fn new() -> Self {
+        Self {
+            current: 0,
+            recycled: Vec::new(),
+        }
+    }
+    /// Allocate a new id.
+    pub fn alloc(&mut self) -> usize {
+        if let Some(id) = self.recycled.pop() {
+            id
+        } else {
+            self.current += 1;
+            self.current - 1
+        }
+    }
+    /// Recycle an id once whatever used it is gone.
+    pub fn dealloc(&mut self, id: usize) {
+        assert!(id < self.current);
+        assert!(
+            !self.recycled.iter().any(|i| *i == id),
+            "id {} has been deallocated!",
+            id
+        );
+        self.recycled.push(id);
+    }
+}
+
+/// Derive a thread's user-stack bottom address from its `tid`.
+///
+/// Stacks are packed upward from `ustack_base` with one guard page
+/// between them, so thread `tid` overflowing its stack faults instead of
+/// corrupting its neighbour.
+fn ustack_bottom_from_tid(ustack_base: usize, tid: usize) -> usize {
+    ustack_base + tid * (PAGE_SIZE + USER_STACK_SIZE)
+}
+
+/// Derive a thread's trap-context page address from its `tid`.
+fn trap_cx_bottom_from_tid(tid: usize) -> usize {
+    TRAP_CONTEXT_BASE - tid * PAGE_SIZE
+}
+
+/// The user-space resources (tid, user stack, trap-context page) owned by
+/// one thread, allocated out of its process's shared `MemorySet`.
+pub struct TaskUserRes {
+    /// This thread's id, unique within its owning process.
+    pub tid: usize,
+    /// Base address threads' user stacks are packed upward from.
+    pub ustack_base: usize,
+    /// The process this thread belongs to.
+    pub process: Weak<ProcessControlBlock>,
+}
+
+impl TaskUserRes {
+    /// Allocate a `tid` for a new thread of `process`, optionally mapping
+    /// its user stack and trap-context page immediately.
+    pub fn new(process: Arc<ProcessControlBlock>, ustack_base: usize, alloc_user_res: bool) -> Self {
+        let tid = process.inner_exclusive_access().alloc_tid();
+        let task_user_res = Self {
+            tid,
+            ustack_base,
+            process: Arc::downgrade(&process),
+        };
+        if alloc_user_res {
+            task_user_res.alloc_user_res();
+        }
+        task_user_res
+    }
+    /// Map this thread's user stack and trap-context page.
+    pub fn alloc_user_res(&self) {
+        let process = self.process.upgrade().unwrap();
+        let mut process_inner = process.inner_exclusive_access();
+        let ustack_bottom = ustack_bottom_from_tid(self.ustack_base, self.tid);
+        let ustack_top = ustack_bottom + USER_STACK_SIZE;
+        process_inner.memory_set.insert_framed_area(
+            ustack_bottom.into(),
+            ustack_top.into(),
+            crate::mm::MapPermission::R | crate::mm::MapPermission::W | crate::mm::MapPermission::U,
+        );
+        let trap_cx_bottom = trap_cx_bottom_from_tid(self.tid);
+        let trap_cx_top = trap_cx_bottom + PAGE_SIZE;
+        process_inner.memory_set.insert_framed_area(
+            trap_cx_bottom.into(),
+            trap_cx_top.into(),
+            crate::mm::MapPermission::R | crate::mm::MapPermission::W,
+        );
+    }
+    /// Unmap this thread's user stack and trap-context page.
+    pub fn dealloc_user_res(&self) {
+        let process = self.process.upgrade().unwrap();
+        let mut process_inner = process.inner_exclusive_access();
+        let ustack_bottom_va: VirtAddr = ustack_bottom_from_tid(self.ustack_base, self.tid).into();
+        process_inner.memory_set.delete_area(
+            ustack_bottom_va,
+            VirtAddr::from(ustack_bottom_va.0 + USER_STACK_SIZE),
+        );
+        let trap_cx_bottom_va: VirtAddr = trap_cx_bottom_from_tid(self.tid).into();
+        process_inner
+            .memory_set
+            .delete_area(trap_cx_bottom_va, VirtAddr::from(trap_cx_bottom_va.0 + PAGE_SIZE));
+    }
+    /// The virtual address of this thread's trap-context page, to be
+    /// translated through the owning process's page table.
+    pub fn trap_cx_user_va(&self) -> usize {
+        trap_cx_bottom_from_tid(self.tid)
+    }
+    /// The physical page backing this thread's trap context, translated
+    /// through the owning process's page table.
+    pub fn trap_cx_ppn(&self) -> PhysPageNum {
+        let process = self.process.upgrade().unwrap();
+        let process_inner = process.inner_exclusive_access();
+        let trap_cx_va = VirtAddr::from(trap_cx_bottom_from_tid(self.tid));
+        process_inner
+            .memory_set
+            .translate(trap_cx_va.into())
+            .unwrap()
+            .ppn()
+    }
+    /// The top of this thread's user stack, i.e. its initial `sp`.
+    pub fn ustack_top(&self) -> usize {
+        ustack_bottom_from_tid(self.ustack_base, self.tid) + USER_STACK_SIZE
+    }
+    /// Recycle this thread's `tid` back to the owning process's allocator.
+    fn dealloc_tid(&self) {
+        let process = self.process.upgrade().unwrap();
+        process.inner_exclusive_access().dealloc_tid(self.tid);
+    }
+}
+
+impl Drop for TaskUserRes {
+    fn drop(&mut self) {
+        self.dealloc_user_res();
+        self.dealloc_tid();
+    }
+}
@@ -0,0 +1,70 @@
+//! The ready-queue task manager
+//!
+//! Holds every `Ready` task that is not currently assigned to a `Processor`.
+//! Decoupling the ready queue from the running task (see [`super::processor`])
+//! is what lets a task outlive the fixed array slot it used to occupy, which
+//! in turn is the prerequisite for `fork`/`spawn` creating tasks dynamically.
+
+use super::task::{stride_cmp, TaskControlBlock};
+use crate::sync::UPSafeCell;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use lazy_static::*;
+
+/// A FIFO-ish ready queue of tasks waiting to run.
+///
+/// `fetch` does not simply pop the front of the queue: it keeps the
+/// stride-scheduling behaviour introduced earlier by removing whichever
+/// queued task has the smallest stride.
+pub struct Manager {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl Manager {
+    /// Create an empty ready queue.
+    pub fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+    /// Add a task to the ready queue.
+    pub fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    /// Remove and return the queued task with the smallest stride, if any.
+    pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let idx = (0..self.ready_queue.len()).min_by(|&a, &b| {
+            let stride_a = self.ready_queue[a].inner_exclusive_access().stride;
+            let stride_b = self.ready_queue[b].inner_exclusive_access().stride;
+            stride_cmp(stride_a, stride_b)
+        })?;
+        self.ready_queue.remove(idx)
+    }
+    /// Drop any queued `Arc` pointing at `task`. Used to pull a thread out
+    /// of the ready queue when its process tears it down out from under it
+    /// (process exit/exec), so it can never be fetched and switched into
+    /// using resources that no longer belong to it.
+    pub fn remove(&mut self, task: &Arc<TaskControlBlock>) {
+        self.ready_queue.retain(|t| !Arc::ptr_eq(t, task));
+    }
+}
+
+lazy_static! {
+    /// a `Manager` global instance through lazy_static!
+    pub static ref MANAGER: UPSafeCell<Manager> = unsafe { UPSafeCell::new(Manager::new()) };
+}
+
+/// Add a task to the ready queue of `MANAGER`.
+pub fn add_task(task: Arc<TaskControlBlock>) {
+    MANAGER.exclusive_access().add(task);
+}
+
+/// Fetch a task to run from the ready queue of `MANAGER`.
+pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
+    MANAGER.exclusive_access().fetch()
+}
+
+/// Remove `task` from the ready queue of `MANAGER`, if it is queued there.
+pub fn remove_task(task: &Arc<TaskControlBlock>) {
+    MANAGER.exclusive_access().remove(task);
+}
@@ -3,345 +3,446 @@
 //! Everything about task management, like starting and switching tasks is
 //! implemented here.
 //!
-//! A single global instance of [`TaskManager`] called `TASK_MANAGER` controls
-//! all the tasks in the operating system.
+//! Scheduling state is split across two structures: [`manager::Manager`]
+//! holds every `Ready` task in a ready queue, and [`processor::Processor`]
+//! holds whichever task is currently `Running` on this CPU plus the idle
+//! control flow's own context. Tasks are shared as `Arc<TaskControlBlock>`,
+//! which is what lets `fork` create new tasks dynamically and lets a
+//! process's parent hold onto its zombie children until they are reaped by
+//! `waitpid`.
+//!
+//! A [`TaskControlBlock`] is a single thread; everything shared by every
+//! thread of a process (address space, pid, parent/children) lives on
+//! [`process::ProcessControlBlock`] instead, joined to its threads through
+//! [`id::TaskUserRes`].
 //!
 //! Be careful when you see `__switch` ASM function in `switch.S`. Control flow around this function
 //! might not be what you expect.
 
 mod context;
+mod id;
+mod manager;
+mod pid;
+mod process;
+mod processor;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
 
-use crate::loader::{get_app_data, get_num_app};
-use crate::sync::UPSafeCell;
+use crate::loader::get_app_data_by_name;
 use crate::timer::get_time_ms;
-use crate::trap::TrapContext;
+use crate::trap::{trap_handler, TrapContext};
 use crate::config::MAX_SYSCALL_NUM;
-use alloc::vec::Vec;
+use alloc::sync::Arc;
 pub use crate::syscall::process::TaskInfo;
 use lazy_static::*;
-use switch::__switch;
 pub use crate::mm::page_table::PageTable;
 pub use crate::mm::address::{PhysAddr, VirtAddr};
 pub use crate::mm::*;
-pub use task::{TaskControlBlock, TaskStatus};
 
 pub use context::TaskContext;
+pub use manager::{add_task, remove_task};
+pub use process::ProcessControlBlock;
+pub use processor::{
+    current_task, current_trap_cx, current_user_token, run_tasks, schedule, take_current_task,
+};
+pub use task::{TaskControlBlock, TaskStatus};
 
-/// The task manager, where all the tasks are managed.
-///
-/// Functions implemented on `TaskManager` deals with all task state transitions
-/// and task context switching. For convenience, you can find wrappers around it
-/// in the module level.
-///
-/// Most of `TaskManager` are hidden behind the field `inner`, to defer
-/// borrowing checks to runtime. You can see examples on how to use `inner` in
-/// existing functions on `TaskManager`.
-pub struct TaskManager {
-    /// total number of tasks
-    num_app: usize,
-    /// use inner value to get mutable access
-    inner: UPSafeCell<TaskManagerInner>,
+lazy_static! {
+    /// The root of the process tree: pid 0, loaded from the "initproc" user
+    /// program. Every other process's `children` eventually chain back to
+    /// it, since exiting processes reparent their own children here.
+    pub static ref INITPROC: Arc<ProcessControlBlock> =
+        ProcessControlBlock::new(get_app_data_by_name("initproc").unwrap());
 }
 
-/// The task manager inner in 'UPSafeCell'
-struct TaskManagerInner {
-    /// task list
-    tasks: Vec<TaskControlBlock>,
-    /// id of current `Running` task
-    current_task: usize,
+/// Add the initial process to the ready queue and start running tasks.
+pub fn run_first_task() -> ! {
+    println!("init TASK_MANAGER");
+    // enable the timer interrupt before any task runs, or preemption
+    // (`check_timer_tick`) would never actually fire
+    crate::trap::init();
+    // force `INITPROC`'s lazy_static initialization, which already enqueues
+    // its main thread
+    lazy_static::initialize(&INITPROC);
+    run_tasks();
 }
 
-lazy_static! {
-    /// a `TaskManager` global instance through lazy_static!
-    pub static ref TASK_MANAGER: TaskManager = {
-        println!("init TASK_MANAGER");
-        let num_app = get_num_app();
-        println!("num_app = {}", num_app);
-        let mut tasks: Vec<TaskControlBlock> = Vec::new();
-        for i in 0..num_app {
-            tasks.push(TaskControlBlock::new(get_app_data(i), i));
-        }
-        TaskManager {
-            num_app,
-            inner: unsafe {
-                UPSafeCell::new(TaskManagerInner {
-                    tasks,
-                    current_task: 0,
-                })
-            },
-        }
+/// Suspend the current 'Running' task and run the next task in task list.
+pub fn suspend_current_and_run_next() {
+    let task = take_current_task().unwrap();
+    let task_cx_ptr = {
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.task_status = TaskStatus::Ready;
+        &mut task_inner.task_cx as *mut TaskContext
     };
+    add_task(task);
+    schedule(task_cx_ptr);
 }
 
-impl TaskManager {
-    /// Run the first task in task list.
-    ///
-    /// Generally, the first task in task list is an idle task (we call it zero process later).
-    /// But in ch4, we load apps statically, so the first task is a real app.
-    fn run_first_task(&self) -> ! {
-        let mut inner = self.inner.exclusive_access();
-        let next_task = &mut inner.tasks[0];
-        next_task.task_status = TaskStatus::Running;
-        let next_task_cx_ptr = &next_task.task_cx as *const TaskContext;
-        drop(inner);
-        let mut _unused = TaskContext::zero_init();
-        // before this, we should drop local variables that must be dropped manually
-        unsafe {
-            __switch(&mut _unused as *mut _, next_task_cx_ptr);
-        }
-        panic!("unreachable in run_first_task!");
-    }
-
-    /// Change the status of current `Running` task into `Ready`.
-    fn mark_current_suspended(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let cur = inner.current_task;
-        inner.tasks[cur].task_status = TaskStatus::Ready;
+/// Exit the current 'Running' thread, recording `exit_code`. If it is the
+/// process's main thread (tid 0), the whole process exits with it, unix
+/// `exit()` style: every other thread is torn down, the process becomes a
+/// zombie for `waitpid`, and its children are reparented to `INITPROC`.
+pub fn exit_current_and_run_next(exit_code: i32) {
+    let task = take_current_task().unwrap();
+    let process = task.process();
+    let tid = task.gettid();
+
+    {
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.exit_code = Some(exit_code);
+        task_inner.task_status = TaskStatus::Zombie;
+        // release this thread's user stack / trap-context page now rather
+        // than waiting for the TCB to drop, so a sibling thread sharing the
+        // address space never faults on a mapping that is about to vanish
+        task_inner.res = None;
     }
 
-    /// Change the status of current `Running` task into `Exited`.
-    fn mark_current_exited(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let cur = inner.current_task;
-        inner.tasks[cur].task_status = TaskStatus::Exited;
+    if tid == 0 {
+        let mut process_inner = process.inner_exclusive_access();
+        process_inner.is_zombie = true;
+        process_inner.exit_code = exit_code;
+
+        // children of the exiting process are reparented to initproc
+        {
+            let mut initproc_inner = INITPROC.inner_exclusive_access();
+            for child in process_inner.children.iter() {
+                child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
+                initproc_inner.children.push(Arc::clone(child));
+            }
+        }
+        process_inner.children.clear();
+
+        // every other thread goes down with the process: pull it out of the
+        // ready queue first so it can never be fetched and switched into
+        // after its `res` (user stack / trap-context mapping, tid) is gone.
+        // Releasing a `res` re-borrows this process's inner state, so that
+        // happens only once `process_inner` is dropped.
+        let tasks = process_inner.tasks.clone();
+        drop(process_inner);
+        for other in tasks.iter().flatten() {
+            if !Arc::ptr_eq(other, &task) {
+                remove_task(other);
+                other.inner_exclusive_access().res = None;
+            }
+        }
     }
+    drop(task);
 
-    /// Find next task to run and return task id.
-    ///
-    /// In this case, we only return the first `Ready` task in task list.
-    fn find_next_task(&self) -> Option<usize> {
-        let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        (current + 1..current + self.num_app + 1)
-            .map(|id| id % self.num_app)
-            .find(|id| inner.tasks[*id].task_status == TaskStatus::Ready)
-    }
+    let mut _unused = TaskContext::zero_init();
+    schedule(&mut _unused as *mut TaskContext);
+}
 
-    /// Get the current 'Running' task's token.
-    fn get_current_token(&self) -> usize {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.current_task].get_user_token()
-    }
+/// Deep-copy the current process into a new child process, enqueue its main
+/// thread, and return the child's pid, or `None` if the current process is
+/// multithreaded (forking those is not supported). Used to implement
+/// `sys_fork`.
+pub fn fork() -> Option<usize> {
+    let process = current_task().expect("no task is currently running").process();
+    let child = process.fork()?;
+    Some(child.getpid())
+}
 
-    /// Get the current 'Running' task's trap contexts.
-    fn get_current_trap_cx(&self) -> &'static mut TrapContext {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.current_task].get_trap_cx()
+/// Replace the current process's address space with the named app's elf
+/// image. Used to implement `sys_exec`.
+pub fn exec(path: &str) -> isize {
+    match get_app_data_by_name(path) {
+        Some(elf_data) => {
+            let task = current_task().expect("no task is currently running");
+            let process = task.process();
+            let tid = task.gettid();
+            process.exec(elf_data, tid);
+            0
+        }
+        None => -1,
     }
+}
 
-    /// Change the current 'Running' task's program break
-    pub fn change_current_program_brk(&self, size: i32) -> Option<usize> {
-        let mut inner = self.inner.exclusive_access();
-        let cur = inner.current_task;
-        inner.tasks[cur].change_program_brk(size)
+/// Reap a zombie child process of the current process matching `pid` (or
+/// any child if `pid == -1`), writing its exit code to `*exit_code_ptr` and
+/// returning its pid. Returns -1 if there is no such child at all, or -2 if
+/// matching children exist but none have exited yet. Used to implement
+/// `sys_waitpid`.
+pub fn waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+    let process = current_task().expect("no task is currently running").process();
+    let mut inner = process.inner_exclusive_access();
+    if !inner
+        .children
+        .iter()
+        .any(|p| pid == -1 || pid as usize == p.getpid())
+    {
+        return -1;
     }
-
-    /// Switch current `Running` task to the task we have found,
-    /// or there is no `Ready` task and we can exit with all applications completed
-    fn run_next_task(&self) {
-        if let Some(next) = self.find_next_task() {
-            let mut inner = self.inner.exclusive_access();
-            let current = inner.current_task;
-            inner.tasks[next].task_status = TaskStatus::Running;
-            inner.current_task = next;
-            let current_task_cx_ptr = &mut inner.tasks[current].task_cx as *mut TaskContext;
-            let next_task_cx_ptr = &inner.tasks[next].task_cx as *const TaskContext;
-            drop(inner);
-            // before this, we should drop local variables that must be dropped manually
+    let pair = inner.children.iter().enumerate().find(|(_, p)| {
+        p.inner_exclusive_access().is_zombie && (pid == -1 || pid as usize == p.getpid())
+    });
+    if let Some((idx, _)) = pair {
+        let child = inner.children.remove(idx);
+        // confirm that child will be deallocated after removing it from
+        // the children list
+        assert_eq!(Arc::strong_count(&child), 1);
+        let found_pid = child.getpid();
+        let exit_code = child.inner_exclusive_access().exit_code;
+        if !exit_code_ptr.is_null() {
             unsafe {
-                __switch(current_task_cx_ptr, next_task_cx_ptr);
+                *exit_code_ptr = exit_code;
             }
-            // go back to user mode
-        } else {
-            panic!("All applications completed!");
         }
+        found_pid as isize
+    } else {
+        -2
     }
-    /// get pa from va
-    pub fn get_pa_from_va(&self, va: usize) -> usize {
-        let inner = self.inner.exclusive_access();
-        let page_table = PageTable::from_token(inner.tasks[inner.current_task].get_user_token());
-        let _va = VirtAddr::from(va);
-        let Some(pa) = page_table.find_pte(_va.clone().floor()).map(|pte| {
-            //println!("translate_va:va = {:?}", va);
-            let aligned_pa: PhysAddr = pte.ppn().into();
-            //println!("translate_va:pa_align = {:?}", aligned_pa);
-            let offset = _va.page_offset();
-            let aligned_pa_usize: usize = aligned_pa.into();
-            (aligned_pa_usize + offset).into()
-        }) else {
-            panic!("Failed to get physical address from virtual address");
-        };
-        pa
-    }
-
-    /// schedule mark
-    pub fn schedule_mark(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let curr_id = inner.current_task;
-        let current_task = &mut inner.tasks[curr_id];
-        if  !current_task.is_scheduled {
-            current_task.is_scheduled = true;
-            current_task.first_scheduled_time = get_time_ms();
-        }
-    }
-
-    
-    ///Todo
-    pub fn get_current_status(&self) -> TaskStatus {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.current_task].task_status
-    }
-    /// TODO
-    pub fn record_this_call(&self, syscall_id: usize) {
-        let mut inner = self.inner.exclusive_access();
-        let curr_task_id = inner.current_task;
-        inner.tasks[curr_task_id].syscall_times[syscall_id] += 1;
-    }
-    /// 获取当前任务的系统调用次数
-    pub fn get_currtask_syscall_time(&self) -> [u32; MAX_SYSCALL_NUM] {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.current_task].syscall_times
-    }
-    /// TODO
-    pub fn get_currtask_first_scheduled_time(&self) -> usize {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.current_task].first_scheduled_time
-    }
+}
 
-    fn mmap(&self, start: usize, len: usize, prot: usize)->isize{
-        if (prot & 0x7 == 0) || (prot & !0x7 != 0) {
-            return -1
-        }
-        let mut right = MapPermission::U;
-        if prot & 0x1 == 0x1 {right = right | MapPermission::R;}
-        if prot & 0x2 == 0x2 {right = right | MapPermission::W;}
-        if prot & 0x4 == 0x4 {right = right | MapPermission::X;}
-        let mut inner = self.inner.exclusive_access();
-        let current_task = inner.current_task;
-        let memory_set = &mut (inner.tasks[current_task].memory_set);
-        let start_va = VirtAddr::from(start);
-        let end_va = VirtAddr::from(start+len);
-        
-        if memory_set.check_used(start_va, end_va) {
-            return -1;
-        } 
-        if start_va.0 & 0xfff != 0{
-            return -1;
-        }
-        memory_set.insert_framed_area(start_va, 
-            end_va, right);
-        0
+/// Create a new thread of the current process, starting at `entry` with
+/// `arg` in `a0` and sharing the calling thread's address space. Returns
+/// the new thread's tid. Used to implement `sys_thread_create`.
+pub fn thread_create(entry: usize, arg: usize) -> isize {
+    let task = current_task().expect("no task is currently running");
+    let process = task.process();
+    let ustack_base = task
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .ustack_base;
+    let new_task = Arc::new(TaskControlBlock::new(Arc::clone(&process), ustack_base, true));
+    let new_task_tid = new_task.gettid();
+
+    let (ustack_top, trap_cx_ppn) = {
+        let inner = new_task.inner_exclusive_access();
+        let res = inner.res.as_ref().unwrap();
+        (res.ustack_top(), res.trap_cx_ppn())
+    };
+    let kstack_top = new_task.kernel_stack.get_top();
+    {
+        let mut new_task_inner = new_task.inner_exclusive_access();
+        new_task_inner.trap_cx_ppn = trap_cx_ppn;
+        let trap_cx = new_task_inner.get_trap_cx();
+        *trap_cx = TrapContext::app_init_context(
+            entry,
+            ustack_top,
+            KERNEL_SPACE.exclusive_access().token(),
+            kstack_top,
+            trap_handler as usize,
+        );
+        trap_cx.x[10] = arg;
     }
 
-    fn munmap(&self, start: usize, len: usize)->isize{
-        let mut inner = self.inner.exclusive_access();
-        let current_task = inner.current_task;
-        let memory_set = &mut (inner.tasks[current_task].memory_set);
-        let start_va = VirtAddr::from(start);
-        let end_va = VirtAddr::from(start+len);
-        if memory_set.check_unused(start_va, end_va) {
-            return -1;
-        }
-        if start_va.0 & 0xfff != 0{
-            return -1;
-        }
-        memory_set.delete_area(start_va, end_va);
-        0
+    let mut process_inner = process.inner_exclusive_access();
+    // extend the thread table so `new_task_tid` has a slot, leaving any
+    // already-reaped tids' slots as `None`
+    while process_inner.tasks.len() <= new_task_tid {
+        process_inner.tasks.push(None);
     }
+    process_inner.tasks[new_task_tid] = Some(Arc::clone(&new_task));
+    drop(process_inner);
 
+    add_task(new_task);
+    new_task_tid as isize
 }
 
-/// get the physical address from the virtual address
-pub fn get_physical_addr(va: usize) -> usize {
-    TASK_MANAGER.get_pa_from_va(va)
-}
-/// Run the first task in task list.
-pub fn run_first_task() {
-    TASK_MANAGER.run_first_task();
+/// The calling thread's tid. Used to implement `sys_gettid`.
+pub fn gettid() -> usize {
+    current_task().expect("no task is currently running").gettid()
 }
 
-/// Switch current `Running` task to the task we have found,
-/// or there is no `Ready` task and we can exit with all applications completed
-fn run_next_task() {
-    TASK_MANAGER.run_next_task();
-}
-
-/// Change the status of current `Running` task into `Ready`.
-fn mark_current_suspended() {
-    TASK_MANAGER.mark_current_suspended();
-}
-
-/// Change the status of current `Running` task into `Exited`.
-fn mark_current_exited() {
-    TASK_MANAGER.mark_current_exited();
-}
-
-/// Suspend the current 'Running' task and run the next task in task list.
-pub fn suspend_current_and_run_next() {
-    mark_current_suspended();
-    run_next_task();
-}
-
-/// Exit the current 'Running' task and run the next task in task list.
-pub fn exit_current_and_run_next() {
-    mark_current_exited();
-    run_next_task();
+/// Wait for thread `tid` of the current process to exit, returning its exit
+/// code and freeing its slot. Returns -1 if `tid` is the caller's own tid or
+/// does not name a thread of this process, or -2 if it exists but hasn't
+/// exited yet. Used to implement `sys_waittid`.
+pub fn waittid(tid: usize) -> isize {
+    let task = current_task().expect("no task is currently running");
+    let process = task.process();
+    if task.gettid() == tid {
+        return -1;
+    }
+    let mut process_inner = process.inner_exclusive_access();
+    let Some(waited_task) = process_inner.tasks.get(tid).cloned().flatten() else {
+        return -1;
+    };
+    let exit_code = {
+        let waited_inner = waited_task.inner_exclusive_access();
+        if waited_inner.task_status != TaskStatus::Zombie {
+            return -2;
+        }
+        waited_inner.exit_code.unwrap()
+    };
+    process_inner.tasks[tid] = None;
+    exit_code as isize
 }
 
-/// Get the current 'Running' task's token.
-pub fn current_user_token() -> usize {
-    TASK_MANAGER.get_current_token()
+/// Account one timer-interrupt tick against the current task, preempting it
+/// once its quantum is exhausted.
+///
+/// Called from the `Interrupt::SupervisorTimer` arm of `trap::trap_handler`
+/// (see `os/src/trap/mod.rs`) so that compute-bound tasks are time-shared
+/// without relying on `sys_yield`; `run_first_task` enables that interrupt
+/// via `trap::init` before any task runs, or this would never fire.
+pub fn check_timer_tick() {
+    let exhausted = {
+        let task = current_task().expect("no task is currently running");
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.time_slice += task::TICK_MS;
+        task_inner.time_slice >= task::QUANTUM_MS
+    };
+    if exhausted {
+        suspend_current_and_run_next();
+    }
 }
 
-/// Get the current 'Running' task's trap contexts.
-pub fn current_trap_cx() -> &'static mut TrapContext {
-    TASK_MANAGER.get_current_trap_cx()
+/// Change the current process's program break
+pub fn change_program_brk(size: i32) -> Option<usize> {
+    let process = current_task().expect("no task is currently running").process();
+    process.inner_exclusive_access().change_program_brk(size)
 }
 
-/// Change the current 'Running' task's program break
-pub fn change_program_brk(size: i32) -> Option<usize> {
-    TASK_MANAGER.change_current_program_brk(size)
+/// Set the stride-scheduling priority of the current task.
+///
+/// Returns the new priority, or -1 if `prio < 2`.
+pub fn set_priority(prio: isize) -> isize {
+    let task = current_task().expect("no task is currently running");
+    task.inner_exclusive_access().set_priority(prio)
 }
 
 /// mark the schedule
 pub fn schedule_mark() {
-    TASK_MANAGER.schedule_mark();
+    let task = current_task().expect("no task is currently running");
+    let mut task_inner = task.inner_exclusive_access();
+    if !task_inner.is_scheduled {
+        task_inner.is_scheduled = true;
+        task_inner.first_scheduled_time = get_time_ms();
+    }
 }
 
 /// record the syscall time
 pub fn record_syscall_time(syscall_id: usize) {
-    TASK_MANAGER.record_this_call(syscall_id);
+    let task = current_task().expect("no task is currently running");
+    task.inner_exclusive_access().syscall_times[syscall_id] += 1;
 }
+
 /// 1
 pub fn get_current_status() -> TaskStatus {
-    TASK_MANAGER.get_current_status()
+    let task = current_task().expect("no task is currently running");
+    let status = task.inner_exclusive_access().task_status;
+    status
 }
+
 /// 1
 pub fn get_currtask_first_scheduled_time() -> usize {
-    TASK_MANAGER.get_currtask_first_scheduled_time()
+    let task = current_task().expect("no task is currently running");
+    let time = task.inner_exclusive_access().first_scheduled_time;
+    time
 }
+
 /// 1
 pub fn get_task_info() -> TaskInfo {
-    let _status = TASK_MANAGER.get_current_status();
-    let _syscall_times = TASK_MANAGER.get_currtask_syscall_time();
-    let _time = get_time_ms() - TASK_MANAGER.get_currtask_first_scheduled_time();
-    let res = TaskInfo {
-        status: _status,
-        syscall_times: _syscall_times,
-        time: _time
+    let task = current_task().expect("no task is currently running");
+    let task_inner = task.inner_exclusive_access();
+    let status = task_inner.task_status;
+    let syscall_times = task_inner.syscall_times;
+    let time = get_time_ms() - task_inner.first_scheduled_time;
+    TaskInfo {
+        status,
+        syscall_times,
+        time,
+    }
+}
+
+/// get the pid of the current task
+pub fn current_pid() -> usize {
+    current_task()
+        .expect("no task is currently running")
+        .process()
+        .getpid()
+}
+
+/// get the physical address from the virtual address, translated through
+/// the current task's page table
+pub fn get_physical_addr(va: usize) -> usize {
+    let process = current_task().expect("no task is currently running").process();
+    let token = process.inner_exclusive_access().get_user_token();
+    let page_table = PageTable::from_token(token);
+    let _va = VirtAddr::from(va);
+    let Some(pa) = page_table.find_pte(_va.clone().floor()).map(|pte| {
+        let aligned_pa: PhysAddr = pte.ppn().into();
+        let offset = _va.page_offset();
+        let aligned_pa_usize: usize = aligned_pa.into();
+        (aligned_pa_usize + offset).into()
+    }) else {
+        panic!("Failed to get physical address from virtual address");
     };
-    res
+    pa
 }
 
 /// 该函数用于开辟文件空间
-pub fn mmap(start: usize, len: usize, prot: usize)->isize{
-    TASK_MANAGER.mmap(start, len, prot)
+///
+/// The range is recorded as a lazy area: no frame is actually allocated
+/// until the first access to one of its pages faults, which
+/// `handle_page_fault` then resolves one page at a time.
+pub fn mmap(start: usize, len: usize, prot: usize) -> isize {
+    if (prot & 0x7 == 0) || (prot & !0x7 != 0) {
+        return -1;
+    }
+    let mut right = MapPermission::U;
+    if prot & 0x1 == 0x1 {
+        right = right | MapPermission::R;
+    }
+    if prot & 0x2 == 0x2 {
+        right = right | MapPermission::W;
+    }
+    if prot & 0x4 == 0x4 {
+        right = right | MapPermission::X;
+    }
+    let process = current_task().expect("no task is currently running").process();
+    let mut process_inner = process.inner_exclusive_access();
+    let memory_set = &mut process_inner.memory_set;
+    let start_va = VirtAddr::from(start);
+    let end_va = VirtAddr::from(start + len);
+
+    if memory_set.check_used(start_va, end_va) {
+        return -1;
+    }
+    if start_va.0 & 0xfff != 0 {
+        return -1;
+    }
+    memory_set.insert_lazy_area(start_va, end_va, right);
+    0
 }
 
 /// 该函数用于释放文件空间
+///
+/// `delete_area` drops whichever areas overlap `[start, start + len)`
+/// regardless of whether their frames were ever actually allocated, so both
+/// a backed `mmap` range and one still entirely unbacked by page faults are
+/// released the same way.
 pub fn munmap(start: usize, len: usize) -> isize {
-    TASK_MANAGER.munmap(start, len)
-}
\ No newline at end of file
+    let process = current_task().expect("no task is currently running").process();
+    let mut process_inner = process.inner_exclusive_access();
+    let memory_set = &mut process_inner.memory_set;
+    let start_va = VirtAddr::from(start);
+    let end_va = VirtAddr::from(start + len);
+    if memory_set.check_unused(start_va, end_va) {
+        return -1;
+    }
+    if start_va.0 & 0xfff != 0 {
+        return -1;
+    }
+    memory_set.delete_area(start_va, end_va);
+    0
+}
+
+/// Resolve a page fault at `fault_va`, caused by an access needing `needed`
+/// permission, by allocating and mapping a frame if it falls within a
+/// lazily-allocated `mmap` range that actually grants `needed`. Returns
+/// `false` if it does not (e.g. a genuine access violation, or a write to a
+/// range mapped without `W`), leaving it to the caller — the page-fault
+/// branch of the trap handler — to kill the task.
+pub fn handle_page_fault(fault_va: usize, needed: MapPermission) -> bool {
+    let process = current_task().expect("no task is currently running").process();
+    let mut process_inner = process.inner_exclusive_access();
+    process_inner
+        .memory_set
+        .alloc_lazy_frame(VirtAddr::from(fault_va), needed)
+}
@@ -0,0 +1,86 @@
+//! Pid and kernel-stack allocation
+//!
+//! Pid and kernel-stack slot are allocated from separate counters: a
+//! process's pid never changes, but with threads each needing their own
+//! kernel stack, a kernel-stack slot is better handed out from its own
+//! pool than derived from the pid (which would leave pid 0's slot
+//! permanently unused by anything past its first thread).
+
+use super::id::RecycleAllocator;
+use crate::config::kernel_stack_position;
+use crate::mm::{MapPermission, VirtAddr, KERNEL_SPACE};
+use crate::sync::UPSafeCell;
+use lazy_static::*;
+
+lazy_static! {
+    /// a `RecycleAllocator` global instance handing out pids
+    static ref PID_ALLOCATOR: UPSafeCell<RecycleAllocator> =
+        unsafe { UPSafeCell::new(RecycleAllocator::new()) };
+    /// a `RecycleAllocator` global instance handing out kernel-stack slots
+    static ref KSTACK_ALLOCATOR: UPSafeCell<RecycleAllocator> =
+        unsafe { UPSafeCell::new(RecycleAllocator::new()) };
+}
+
+/// An RAII handle for an allocated pid: the pid is returned to the
+/// allocator automatically when the handle is dropped.
+pub struct PidHandle(pub usize);
+
+impl Drop for PidHandle {
+    fn drop(&mut self) {
+        PID_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
+
+/// Allocate a new pid, wrapped in an RAII handle.
+pub fn pid_alloc() -> PidHandle {
+    PidHandle(PID_ALLOCATOR.exclusive_access().alloc())
+}
+
+/// A kernel stack, mapped into kernel space at a location derived from a
+/// freshly allocated kernel-stack slot (see `kernel_stack_position`), with
+/// a guard page below it to catch overflow.
+pub struct KernelStack(pub usize);
+
+/// Allocate a fresh kernel-stack slot and map it, wrapped in an RAII handle.
+pub fn kstack_alloc() -> KernelStack {
+    let kstack_id = KSTACK_ALLOCATOR.exclusive_access().alloc();
+    let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(kstack_id);
+    KERNEL_SPACE.exclusive_access().insert_framed_area(
+        kernel_stack_bottom.into(),
+        kernel_stack_top.into(),
+        MapPermission::R | MapPermission::W,
+    );
+    KernelStack(kstack_id)
+}
+
+impl KernelStack {
+    /// Push `value` onto the top of this kernel stack and return a pointer
+    /// to it.
+    pub fn push_on_top<T>(&self, value: T) -> *mut T
+    where
+        T: Sized,
+    {
+        let kernel_stack_top = self.get_top();
+        let ptr_mut = (kernel_stack_top - core::mem::size_of::<T>()) as *mut T;
+        unsafe {
+            *ptr_mut = value;
+        }
+        ptr_mut
+    }
+    /// The top (highest) virtual address of this kernel stack.
+    pub fn get_top(&self) -> usize {
+        let (_, kernel_stack_top) = kernel_stack_position(self.0);
+        kernel_stack_top
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        let (kernel_stack_bottom, _) = kernel_stack_position(self.0);
+        let kernel_stack_bottom_va: VirtAddr = kernel_stack_bottom.into();
+        KERNEL_SPACE
+            .exclusive_access()
+            .remove_area_with_start_vpn(kernel_stack_bottom_va.into());
+        KSTACK_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
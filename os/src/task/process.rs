@@ -0,0 +1,257 @@
+//! Process control block: everything that lives at process, not thread,
+//! granularity.
+//!
+//! Address space, parent/child links and exit status belong here rather
+//! than on [`TaskControlBlock`] so that several threads of one process can
+//! share them while keeping their own trap context, kernel stack and
+//! scheduling state independent.
+
+use super::id::{RecycleAllocator, TaskUserRes};
+use super::manager::{add_task, remove_task};
+use super::pid::{pid_alloc, PidHandle};
+use super::task::{TaskControlBlock, TaskStatus};
+use crate::mm::{MemorySet, VirtAddr, KERNEL_SPACE};
+use crate::sync::UPSafeCell;
+use crate::trap::{trap_handler, TrapContext};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::cell::RefMut;
+
+/// The process control block (PCB) of a process.
+pub struct ProcessControlBlock {
+    /// Process identifier, allocated once and held for this process's
+    /// lifetime.
+    pub pid: PidHandle,
+    /// Mutable inner state, behind a `UPSafeCell` so the process can be
+    /// shared via `Arc` while still being mutated in place.
+    inner: UPSafeCell<ProcessControlBlockInner>,
+}
+
+/// Mutable state of a [`ProcessControlBlock`].
+pub struct ProcessControlBlockInner {
+    /// Whether this process has exited and is waiting to be reaped.
+    pub is_zombie: bool,
+    /// Address space shared by every thread of this process.
+    pub memory_set: MemorySet,
+    /// Heap bottom
+    pub heap_bottom: usize,
+    /// Program break
+    pub program_brk: usize,
+    /// Parent process, if any. A `Weak` reference so that a parent/child
+    /// cycle does not keep either side alive forever.
+    pub parent: Option<Weak<ProcessControlBlock>>,
+    /// Child processes, in the order they were forked.
+    pub children: Vec<Arc<ProcessControlBlock>>,
+    /// Exit code recorded by the thread whose exit tore down the whole
+    /// process, read by `waitpid`.
+    pub exit_code: i32,
+    /// Every thread of this process, indexed by `tid`. A slot becomes
+    /// `None` once that `tid` has been reaped by `waittid`.
+    pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
+    /// Hands out `tid`s to new threads of this process.
+    task_res_allocator: RecycleAllocator,
+}
+
+impl ProcessControlBlockInner {
+    /// Get the user token (satp) shared by every thread of this process.
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set.token()
+    }
+    /// Allocate a `tid` for a new thread.
+    pub fn alloc_tid(&mut self) -> usize {
+        self.task_res_allocator.alloc()
+    }
+    /// Recycle a `tid` once its thread has exited and been reaped.
+    pub fn dealloc_tid(&mut self, tid: usize) {
+        self.task_res_allocator.dealloc(tid)
+    }
+    /// Number of threads still alive, i.e. not yet reaped by `waittid`.
+    pub fn thread_count(&self) -> usize {
+        self.tasks.iter().filter(|t| t.is_some()).count()
+    }
+    /// Get a live thread by `tid`.
+    pub fn get_task(&self, tid: usize) -> Arc<TaskControlBlock> {
+        self.tasks[tid].as_ref().unwrap().clone()
+    }
+    /// change the location of the program break. return None if failed.
+    pub fn change_program_brk(&mut self, size: i32) -> Option<usize> {
+        let old_break = self.program_brk;
+        let new_brk = self.program_brk as isize + size as isize;
+        if new_brk < self.heap_bottom as isize {
+            return None;
+        }
+        let result = if size < 0 {
+            self.memory_set
+                .shrink_to(VirtAddr::from(self.heap_bottom), VirtAddr::from(new_brk as usize))
+        } else {
+            self.memory_set
+                .append_to(VirtAddr::from(self.heap_bottom), VirtAddr::from(new_brk as usize))
+        };
+        if result {
+            self.program_brk = new_brk as usize;
+            Some(old_break)
+        } else {
+            None
+        }
+    }
+}
+
+impl ProcessControlBlock {
+    /// Exclusive, runtime-checked access to this process's mutable state.
+    pub fn inner_exclusive_access(&self) -> RefMut<'_, ProcessControlBlockInner> {
+        self.inner.exclusive_access()
+    }
+    /// This process's pid.
+    pub fn getpid(&self) -> usize {
+        self.pid.0
+    }
+    /// Load `elf_data` as a brand-new process with a single main thread
+    /// (tid 0), and enqueue that thread to run.
+    pub fn new(elf_data: &[u8]) -> Arc<Self> {
+        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+        let pid_handle = pid_alloc();
+        let process = Arc::new(Self {
+            pid: pid_handle,
+            inner: unsafe {
+                UPSafeCell::new(ProcessControlBlockInner {
+                    is_zombie: false,
+                    memory_set,
+                    heap_bottom: ustack_base,
+                    program_brk: ustack_base,
+                    parent: None,
+                    children: Vec::new(),
+                    exit_code: 0,
+                    tasks: Vec::new(),
+                    task_res_allocator: RecycleAllocator::new(),
+                })
+            },
+        });
+        let task = Arc::new(TaskControlBlock::new(Arc::clone(&process), ustack_base, true));
+        let ustack_top = task
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .ustack_top();
+        let kstack_top = task.kernel_stack.get_top();
+        let trap_cx = task.inner_exclusive_access().get_trap_cx();
+        *trap_cx = TrapContext::app_init_context(
+            entry_point,
+            ustack_top,
+            KERNEL_SPACE.exclusive_access().token(),
+            kstack_top,
+            trap_handler as usize,
+        );
+        process.inner_exclusive_access().tasks.push(Some(Arc::clone(&task)));
+        add_task(task);
+        process
+    }
+    /// Replace this process's address space with a freshly loaded elf
+    /// image, tearing down every thread but the calling one and resetting
+    /// it to the new entry point and user stack. Used to implement
+    /// `sys_exec`.
+    pub fn exec(self: &Arc<Self>, elf_data: &[u8], calling_tid: usize) {
+        let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
+
+        let task = self.inner_exclusive_access().get_task(calling_tid);
+        // every thread but the calling one is torn down: its resources were
+        // allocated out of the address space we are about to replace. Every
+        // thread's `res`, the caller's included, is released while that old
+        // address space and tid allocator are still in place, and outside
+        // any borrow of this process's inner state, since releasing a
+        // `TaskUserRes` needs to re-borrow it.
+        let old_tasks = core::mem::replace(&mut self.inner_exclusive_access().tasks, Vec::new());
+        // pull every torn-down sibling out of the ready queue first, so a
+        // `Ready` one can never be fetched and switched into once its `res`
+        // (user stack / trap-context mapping, tid) is gone below
+        for other in old_tasks.iter().flatten() {
+            if !Arc::ptr_eq(other, &task) {
+                remove_task(other);
+            }
+            let old_res = other.inner_exclusive_access().res.take();
+            drop(old_res);
+        }
+        drop(old_tasks);
+        {
+            let mut inner = self.inner_exclusive_access();
+            inner.memory_set = memory_set;
+            inner.heap_bottom = ustack_base;
+            inner.program_brk = ustack_base;
+            inner.task_res_allocator = RecycleAllocator::new();
+        }
+
+        // this re-allocates tid 0 from the fresh allocator above, so the
+        // surviving thread keeps being the process's main thread
+        let new_res = TaskUserRes::new(Arc::clone(self), ustack_base, true);
+        let trap_cx_ppn = new_res.trap_cx_ppn();
+        let ustack_top = new_res.ustack_top();
+        let kstack_top = task.kernel_stack.get_top();
+        {
+            let mut task_inner = task.inner_exclusive_access();
+            task_inner.res = Some(new_res);
+            task_inner.trap_cx_ppn = trap_cx_ppn;
+            let trap_cx = task_inner.get_trap_cx();
+            *trap_cx = TrapContext::app_init_context(
+                entry_point,
+                ustack_top,
+                KERNEL_SPACE.exclusive_access().token(),
+                kstack_top,
+                trap_handler as usize,
+            );
+        }
+        // `inner.tasks` was emptied above; the surviving thread needs to be
+        // tracked again at its (tid 0) slot so `get_task`/`fork` can find it
+        self.inner_exclusive_access().tasks.push(Some(task));
+    }
+    /// Fork a single-threaded child process whose address space is a deep
+    /// copy of the parent's. Forking a multithreaded process is not
+    /// supported, mirroring the restriction the user-space thread library
+    /// itself enforces, and returns `None` rather than panicking so the
+    /// caller can fail the syscall gracefully. Used to implement `sys_fork`.
+    pub fn fork(self: &Arc<Self>) -> Option<Arc<Self>> {
+        let mut parent_inner = self.inner_exclusive_access();
+        if parent_inner.thread_count() != 1 {
+            return None;
+        }
+        let memory_set = MemorySet::from_existing_user(&parent_inner.memory_set);
+        let pid_handle = pid_alloc();
+        let child = Arc::new(Self {
+            pid: pid_handle,
+            inner: unsafe {
+                UPSafeCell::new(ProcessControlBlockInner {
+                    is_zombie: false,
+                    memory_set,
+                    heap_bottom: parent_inner.heap_bottom,
+                    program_brk: parent_inner.program_brk,
+                    parent: Some(Arc::downgrade(self)),
+                    children: Vec::new(),
+                    exit_code: 0,
+                    tasks: Vec::new(),
+                    task_res_allocator: RecycleAllocator::new(),
+                })
+            },
+        });
+        parent_inner.children.push(Arc::clone(&child));
+
+        let parent_main = parent_inner.get_task(0);
+        let ustack_base = parent_main
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .unwrap()
+            .ustack_base;
+        // the child's address space is already a full copy of the
+        // parent's, so its main thread reuses the same tid/ustack/trap-cx
+        // layout rather than mapping a second copy of them
+        let task = Arc::new(TaskControlBlock::new(Arc::clone(&child), ustack_base, false));
+        let trap_cx_ppn = task.inner_exclusive_access().res.as_ref().unwrap().trap_cx_ppn();
+        task.inner_exclusive_access().trap_cx_ppn = trap_cx_ppn;
+        let trap_cx = task.inner_exclusive_access().get_trap_cx();
+        trap_cx.kernel_sp = task.kernel_stack.get_top();
+        trap_cx.x[10] = 0;
+
+        child.inner_exclusive_access().tasks.push(Some(Arc::clone(&task)));
+        add_task(task);
+        Some(child)
+    }
+}
@@ -0,0 +1,113 @@
+//! The per-CPU task processor
+//!
+//! Owns whichever task is currently `Running` on this CPU, plus the idle
+//! control flow's own `TaskContext` used as the switch-out target when there
+//! is nothing left to run. Splitting this out of the old monolithic
+//! `TaskManager` is what lets the ready queue in [`super::manager`] hold
+//! tasks that no CPU is currently executing.
+
+use super::manager::fetch_task;
+use super::switch::__switch;
+use super::task::{TaskControlBlock, TaskStatus};
+use super::TaskContext;
+use crate::sync::UPSafeCell;
+use crate::trap::TrapContext;
+use alloc::sync::Arc;
+use lazy_static::*;
+
+/// Per-CPU task processor.
+pub struct Processor {
+    /// The task currently `Running` on this CPU, if any.
+    current: Option<Arc<TaskControlBlock>>,
+    /// The context of the idle control flow that drives `run_tasks`, used as
+    /// the switch-out target when no task is current.
+    idle_task_cx: TaskContext,
+}
+
+impl Processor {
+    /// Create an idle processor with no current task.
+    pub fn new() -> Self {
+        Self {
+            current: None,
+            idle_task_cx: TaskContext::zero_init(),
+        }
+    }
+    fn get_idle_task_cx_ptr(&mut self) -> *mut TaskContext {
+        &mut self.idle_task_cx as *mut _
+    }
+    /// Take the current task out, leaving `None` in its place.
+    pub fn take_current(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.current.take()
+    }
+    /// Clone an `Arc` reference to the current task, if any.
+    pub fn current(&self) -> Option<Arc<TaskControlBlock>> {
+        self.current.as_ref().map(Arc::clone)
+    }
+}
+
+lazy_static! {
+    /// a `Processor` global instance through lazy_static!
+    pub static ref PROCESSOR: UPSafeCell<Processor> = unsafe { UPSafeCell::new(Processor::new()) };
+}
+
+/// The idle control flow: repeatedly fetch a ready task and run it to
+/// completion (or until it suspends/exits), forever.
+pub fn run_tasks() -> ! {
+    loop {
+        let mut processor = PROCESSOR.exclusive_access();
+        if let Some(task) = fetch_task() {
+            let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
+            let next_task_cx_ptr = {
+                let mut task_inner = task.inner_exclusive_access();
+                task_inner.task_status = TaskStatus::Running;
+                task_inner.stride = task_inner.stride.wrapping_add(task_inner.pass);
+                task_inner.time_slice = 0;
+                &task_inner.task_cx as *const TaskContext
+            };
+            processor.current = Some(task);
+            drop(processor);
+            crate::timer::set_next_trigger();
+            unsafe {
+                __switch(idle_task_cx_ptr, next_task_cx_ptr);
+            }
+        }
+    }
+}
+
+/// Take the current task out of `PROCESSOR`, leaving `None` in its place.
+pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
+    PROCESSOR.exclusive_access().take_current()
+}
+
+/// Clone an `Arc` reference to the task currently running on `PROCESSOR`.
+pub fn current_task() -> Option<Arc<TaskControlBlock>> {
+    PROCESSOR.exclusive_access().current()
+}
+
+/// Get the current task's user token (shared by every thread of its process).
+pub fn current_user_token() -> usize {
+    current_task()
+        .expect("no task is currently running")
+        .process()
+        .inner_exclusive_access()
+        .get_user_token()
+}
+
+/// Get the current task's trap context.
+pub fn current_trap_cx() -> &'static mut TrapContext {
+    current_task()
+        .expect("no task is currently running")
+        .inner_exclusive_access()
+        .get_trap_cx()
+}
+
+/// Switch out of the current task's context and back into the idle control
+/// flow, which will go fetch the next task to run.
+pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
+    let mut processor = PROCESSOR.exclusive_access();
+    let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
+    drop(processor);
+    unsafe {
+        __switch(switched_task_cx_ptr, idle_task_cx_ptr);
+    }
+}
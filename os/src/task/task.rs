@@ -0,0 +1,183 @@
+//! Types related to task (thread) management
+
+use super::id::TaskUserRes;
+use super::pid::{kstack_alloc, KernelStack};
+use super::process::ProcessControlBlock;
+use super::TaskContext;
+use crate::config::MAX_SYSCALL_NUM;
+use crate::mm::PhysPageNum;
+use crate::sync::UPSafeCell;
+use crate::trap::TrapContext;
+use alloc::sync::{Arc, Weak};
+use core::cell::RefMut;
+
+/// Default priority assigned to a newly created task.
+///
+/// Used to derive the initial `pass` for the stride scheduler: a task that
+/// never calls `sys_set_priority` is scheduled as if it had asked for this
+/// priority.
+pub const DEFAULT_PRIORITY: usize = 16;
+
+/// The stride scheduler's common multiple.
+///
+/// Every task's `pass` is `BIG_STRIDE / priority`, so a higher priority
+/// yields a smaller pass and therefore more frequent scheduling. Chosen
+/// large enough that `pass` stays meaningfully granular down to the lowest
+/// allowed priority.
+pub const BIG_STRIDE: usize = 0x10000;
+
+/// Length, in milliseconds, of one timer-interrupt tick.
+///
+/// The timer is rearmed for this long every time it fires, regardless of
+/// whether the current task is actually preempted.
+pub const TICK_MS: usize = 10;
+
+/// Length, in milliseconds, of a task's preemptive scheduling quantum.
+///
+/// A `Running` task accumulates ticks in its `time_slice`; once that total
+/// reaches `QUANTUM_MS` it is forced to yield even if it never calls
+/// `sys_yield`, giving unmodified compute-bound programs fair time-sharing.
+pub const QUANTUM_MS: usize = 50;
+
+/// Compare two stride-scheduler strides with wraparound tolerance.
+///
+/// Relies on the invariant that the gap between the largest and smallest
+/// stride in the system never exceeds `BIG_STRIDE`, so a "negative" wrapped
+/// difference larger than `BIG_STRIDE` means the subtraction wrapped around
+/// and the sign should be flipped.
+pub fn stride_cmp(a: usize, b: usize) -> core::cmp::Ordering {
+    if a.wrapping_sub(b) > BIG_STRIDE {
+        core::cmp::Ordering::Less
+    } else if b.wrapping_sub(a) > BIG_STRIDE {
+        core::cmp::Ordering::Greater
+    } else {
+        a.cmp(&b)
+    }
+}
+
+/// The task control block (TCB) of a single thread.
+///
+/// Address space and pid live at process granularity on the
+/// owning [`ProcessControlBlock`]; everything here is per-thread: the user
+/// stack/trap-context page ([`TaskUserRes`]), the kernel stack, and
+/// scheduling state. Shared via `Arc` between the ready queue, the
+/// `Processor`, and the owning process's thread list.
+pub struct TaskControlBlock {
+    /// The process this thread belongs to.
+    pub process: Weak<ProcessControlBlock>,
+    /// This thread's kernel stack.
+    pub kernel_stack: KernelStack,
+    /// Mutable inner state, behind a `UPSafeCell` so the thread can be
+    /// shared via `Arc` while still being mutated in place.
+    inner: UPSafeCell<TaskControlBlockInner>,
+}
+
+/// Mutable state of a [`TaskControlBlock`].
+pub struct TaskControlBlockInner {
+    /// This thread's user-space resources (tid, user stack, trap-context
+    /// page). `None` once they have been released on thread exit, while the
+    /// TCB itself lingers as a zombie for `waittid` to reap.
+    pub res: Option<TaskUserRes>,
+    /// The phys page number backing this thread's trap context.
+    pub trap_cx_ppn: PhysPageNum,
+    /// Save task context
+    pub task_cx: TaskContext,
+    /// Maintain the execution status of this thread
+    pub task_status: TaskStatus,
+    /// Exit code recorded on thread exit, read by `waittid`.
+    pub exit_code: Option<i32>,
+    /// The numbers of every syscall called by this thread
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    /// Whether this thread has ever been scheduled
+    pub is_scheduled: bool,
+    /// The timestamp (in ms) this thread was first scheduled
+    pub first_scheduled_time: usize,
+    /// Stride-scheduling priority, set via `sys_set_priority`
+    pub priority: usize,
+    /// Current stride, advanced by `pass` every time this thread is scheduled
+    pub stride: usize,
+    /// Stride increment added on each schedule, `BIG_STRIDE / priority`
+    pub pass: usize,
+    /// Milliseconds of run time accumulated since this thread was last
+    /// scheduled; reset to 0 whenever it is (re)scheduled, and checked
+    /// against `QUANTUM_MS` on every timer tick
+    pub time_slice: usize,
+}
+
+impl TaskControlBlockInner {
+    /// Get the trap context
+    pub fn get_trap_cx(&self) -> &'static mut TrapContext {
+        self.trap_cx_ppn.get_mut()
+    }
+    /// Whether this thread has exited and is waiting to be reaped.
+    pub fn is_zombie(&self) -> bool {
+        self.task_status == TaskStatus::Zombie
+    }
+    /// Set the priority used by the stride scheduler, rejecting values below 2.
+    ///
+    /// Returns the new priority on success, or -1 if `prio` is out of range.
+    pub fn set_priority(&mut self, prio: isize) -> isize {
+        if prio < 2 {
+            return -1;
+        }
+        self.priority = prio as usize;
+        self.pass = BIG_STRIDE / self.priority;
+        prio
+    }
+}
+
+impl TaskControlBlock {
+    /// Exclusive, runtime-checked access to this thread's mutable state.
+    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
+        self.inner.exclusive_access()
+    }
+    /// This thread's tid, unique within its owning process.
+    pub fn gettid(&self) -> usize {
+        self.inner_exclusive_access().res.as_ref().unwrap().tid
+    }
+    /// The process this thread belongs to.
+    pub fn process(&self) -> Arc<ProcessControlBlock> {
+        self.process.upgrade().unwrap()
+    }
+    /// Create a new thread for `process`, allocating its user resources
+    /// (unless `alloc_user_res` is false, e.g. for a forked child's main
+    /// thread, whose user stack and trap-context page already exist in the
+    /// address space copied from its parent) and a fresh kernel stack.
+    pub fn new(process: Arc<ProcessControlBlock>, ustack_base: usize, alloc_user_res: bool) -> Self {
+        let res = TaskUserRes::new(process, ustack_base, alloc_user_res);
+        let trap_cx_ppn = res.trap_cx_ppn();
+        let kernel_stack = kstack_alloc();
+        let kstack_top = kernel_stack.get_top();
+        Self {
+            process: res.process.clone(),
+            kernel_stack,
+            inner: unsafe {
+                UPSafeCell::new(TaskControlBlockInner {
+                    res: Some(res),
+                    trap_cx_ppn,
+                    task_cx: TaskContext::goto_trap_return(kstack_top),
+                    task_status: TaskStatus::Ready,
+                    exit_code: None,
+                    syscall_times: [0; MAX_SYSCALL_NUM],
+                    is_scheduled: false,
+                    first_scheduled_time: 0,
+                    priority: DEFAULT_PRIORITY,
+                    stride: 0,
+                    pass: BIG_STRIDE / DEFAULT_PRIORITY,
+                    time_slice: 0,
+                })
+            },
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+/// The execution status of a thread
+pub enum TaskStatus {
+    /// ready to run
+    Ready,
+    /// currently running
+    Running,
+    /// exited, but not yet reaped by `waittid`
+    Zombie,
+}
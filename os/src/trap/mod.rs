@@ -0,0 +1,66 @@
+//! Trap handling: dispatch on the trap cause, and the timer-interrupt setup
+//! needed for preemptive scheduling.
+
+mod context;
+
+pub use context::TrapContext;
+
+use crate::mm::MapPermission;
+use crate::syscall::syscall;
+use crate::task::{check_timer_tick, exit_current_and_run_next, handle_page_fault};
+use crate::timer::set_next_trigger;
+use riscv::register::{
+    scause::{self, Exception, Interrupt, Trap},
+    sie, stval,
+};
+
+/// Enable the timer interrupt so `check_timer_tick` is actually reached; the
+/// rest of trap entry (`stvec` and the other interrupt/exception enables) is
+/// assumed to already be wired up by the boot path this series doesn't touch.
+pub fn init() {
+    unsafe {
+        sie::set_stimer();
+    }
+    set_next_trigger();
+}
+
+/// Handle the trap that brought `cx` in, returning the (possibly
+/// unchanged) context to resume into.
+pub fn trap_handler(cx: &mut TrapContext) -> &mut TrapContext {
+    let scause = scause::read();
+    let stval = stval::read();
+    match scause.cause() {
+        Trap::Exception(Exception::UserEnvCall) => {
+            cx.sepc += 4;
+            cx.x[10] = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]) as usize;
+        }
+        Trap::Exception(Exception::StoreFault) | Trap::Exception(Exception::StorePageFault) => {
+            if !handle_page_fault(stval, MapPermission::W) {
+                exit_current_and_run_next(-2);
+            }
+        }
+        Trap::Exception(Exception::LoadFault) | Trap::Exception(Exception::LoadPageFault) => {
+            if !handle_page_fault(stval, MapPermission::R) {
+                exit_current_and_run_next(-2);
+            }
+        }
+        Trap::Exception(Exception::InstructionPageFault) => {
+            if !handle_page_fault(stval, MapPermission::X) {
+                exit_current_and_run_next(-2);
+            }
+        }
+        Trap::Exception(Exception::IllegalInstruction) => {
+            exit_current_and_run_next(-3);
+        }
+        Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            // this is what actually drives preemptive time-slice scheduling;
+            // see `check_timer_tick`'s doc comment in `task/mod.rs`
+            set_next_trigger();
+            check_timer_tick();
+        }
+        _ => {
+            panic!("Unsupported trap {:?}, stval = {:#x}!", scause.cause(), stval);
+        }
+    }
+    cx
+}